@@ -0,0 +1,409 @@
+//! Shared helpers for the bonecoin wallet tests
+//!
+//! Declare this module next to the tests module (`#[cfg(test)] mod testkit;`) so every
+//! test file can bring the helpers in with `use super::testkit::*;`. See the readme.
+
+use super::*;
+use std::collections::HashMap;
+
+/// Builds a test transaction and hands out the coins it creates by name, so tests
+/// don't have to count output indices by hand when calling `coin_id`.
+///
+/// ```ignore
+/// let mint = TxSpec::new()
+///     .dummy_input()
+///     .mint("alice", Address::Alice, 100)
+///     .build();
+/// let alice_coin = mint.coin("alice");
+/// let spend = TxSpec::new().spend(alice_coin).mint("bob", Address::Bob, 60).burn();
+/// ```
+pub(crate) struct TxSpec {
+    inputs: Vec<Input>,
+    outputs: Vec<(&'static str, Coin)>,
+}
+
+/// A transaction built by `TxSpec`, together with the ids of the coins it created.
+pub(crate) struct BuiltTx {
+    pub(crate) tx: Transaction,
+    coins: Vec<(&'static str, CoinId)>,
+}
+
+impl TxSpec {
+    pub(crate) fn new() -> Self {
+        TxSpec {
+            inputs: vec![],
+            outputs: vec![],
+        }
+    }
+
+    /// Adds a made up input, which keeps otherwise identical transactions unique.
+    pub(crate) fn dummy_input(mut self) -> Self {
+        self.inputs.push(Input::dummy());
+        self
+    }
+
+    /// Spends the given coin. The signature is invalid because the wallet
+    /// doesn't check it for transactions that are already in a block.
+    pub(crate) fn spend(mut self, coin_id: CoinId) -> Self {
+        self.inputs.push(Input {
+            coin_id,
+            signature: Signature::Invalid,
+        });
+        self
+    }
+
+    /// Creates a coin that can later be looked up with `BuiltTx::coin(name)`.
+    pub(crate) fn mint(mut self, name: &'static str, owner: Address, value: u64) -> Self {
+        assert!(
+            self.outputs.iter().all(|(existing, _)| *existing != name),
+            "coin name {name} is used twice in the same transaction"
+        );
+        self.outputs.push((name, Coin { owner, value }));
+        self
+    }
+
+    pub(crate) fn build(self) -> BuiltTx {
+        let (names, outputs): (Vec<_>, Vec<_>) = self.outputs.into_iter().unzip();
+        let tx = Transaction {
+            inputs: self.inputs,
+            outputs,
+        };
+        let coins = names
+            .into_iter()
+            .enumerate()
+            .map(|(index, name)| (name, tx.coin_id(index)))
+            .collect();
+        BuiltTx { tx, coins }
+    }
+
+    /// Like `build`, for transactions that destroy value: whatever the inputs hold
+    /// beyond the minted coins is burned. Panics if nothing is spent, since then there
+    /// is nothing to burn.
+    pub(crate) fn burn(self) -> BuiltTx {
+        assert!(
+            !self.inputs.is_empty(),
+            "a burning transaction has to spend at least one coin"
+        );
+        self.build()
+    }
+}
+
+impl BuiltTx {
+    /// Returns the id of the coin minted under `name`.
+    pub(crate) fn coin(&self, name: &str) -> CoinId {
+        self.coins
+            .iter()
+            .find(|(existing, _)| *existing == name)
+            .map(|(_, coin_id)| *coin_id)
+            .unwrap_or_else(|| panic!("no coin named {name} in this transaction"))
+    }
+}
+
+/// A `MockNode` that also remembers the blocks added to it, so `NaiveWallet` can walk the
+/// best chain. The tests only ever write to `MockNode`, so its read side is left to the
+/// wallet crate. Add blocks through this wrapper and sync `Wallet` against `mock`.
+pub(crate) struct RecordingNode {
+    pub(crate) mock: MockNode,
+    /// Id, parent id and transactions of every block after genesis.
+    blocks: Vec<(BlockId, BlockId, Vec<Transaction>)>,
+    best: BlockId,
+}
+
+impl RecordingNode {
+    pub(crate) fn new() -> Self {
+        RecordingNode {
+            mock: MockNode::new(),
+            blocks: vec![],
+            best: Block::genesis().id(),
+        }
+    }
+
+    pub(crate) fn add_block(&mut self, parent: BlockId, transactions: Vec<Transaction>) -> BlockId {
+        let block_id = self.mock.add_block(parent, transactions.clone());
+        self.blocks.push((block_id, parent, transactions));
+        block_id
+    }
+
+    pub(crate) fn add_block_as_best(
+        &mut self,
+        parent: BlockId,
+        transactions: Vec<Transaction>,
+    ) -> BlockId {
+        let block_id = self.mock.add_block_as_best(parent, transactions.clone());
+        self.blocks.push((block_id, parent, transactions));
+        self.best = block_id;
+        block_id
+    }
+
+    /// The id and transactions of every block after genesis on the best chain, in order.
+    pub(crate) fn best_chain(&self) -> Vec<(BlockId, Vec<Transaction>)> {
+        let mut chain = vec![];
+        let mut current = self.best;
+        while current != Block::genesis().id() {
+            let (block_id, parent, transactions) = self
+                .blocks
+                .iter()
+                .find(|(block_id, _, _)| *block_id == current)
+                .expect("every block on the best chain was recorded");
+            chain.push((*block_id, transactions.clone()));
+            current = *parent;
+        }
+        chain.reverse();
+        chain
+    }
+}
+
+/// A deliberately simple wallet to differential test `Wallet` against. It forgets
+/// everything and rescans the whole best chain on every sync, so it has no reorg logic
+/// that could share a bug with the real wallet. Its query methods mirror `Wallet`.
+pub(crate) struct NaiveWallet {
+    addresses: Vec<Address>,
+    best: (BlockId, u64),
+    utxos: HashMap<CoinId, Coin>,
+}
+
+impl NaiveWallet {
+    pub(crate) fn new(addresses: impl IntoIterator<Item = Address>) -> Self {
+        NaiveWallet {
+            addresses: addresses.into_iter().collect(),
+            best: (Block::genesis().id(), 0),
+            utxos: HashMap::new(),
+        }
+    }
+
+    /// Rescans the best chain of `node` from genesis.
+    pub(crate) fn sync(&mut self, node: &RecordingNode) {
+        self.best = (Block::genesis().id(), 0);
+        self.utxos.clear();
+
+        for (block_id, transactions) in node.best_chain() {
+            for tx in &transactions {
+                for input in &tx.inputs {
+                    self.utxos.remove(&input.coin_id);
+                }
+                for (index, coin) in tx.outputs.iter().enumerate() {
+                    if self.addresses.contains(&coin.owner) {
+                        self.utxos.insert(tx.coin_id(index), coin.clone());
+                    }
+                }
+            }
+            self.best = (block_id, self.best.1 + 1);
+        }
+    }
+
+    pub(crate) fn addresses(&self) -> &[Address] {
+        &self.addresses
+    }
+
+    pub(crate) fn best_height(&self) -> u64 {
+        self.best.1
+    }
+
+    pub(crate) fn best_hash(&self) -> BlockId {
+        self.best.0
+    }
+
+    pub(crate) fn total_assets_of(&self, address: Address) -> Result<u64, WalletError> {
+        Ok(self
+            .all_coins_of(address)?
+            .iter()
+            .map(|(_, value)| value)
+            .sum())
+    }
+
+    pub(crate) fn net_worth(&self) -> u64 {
+        self.utxos.values().map(|coin| coin.value).sum()
+    }
+
+    pub(crate) fn all_coins_of(&self, address: Address) -> Result<Vec<(CoinId, u64)>, WalletError> {
+        if self.addresses.is_empty() {
+            return Err(WalletError::NoOwnedAddresses);
+        }
+        if !self.addresses.contains(&address) {
+            return Err(WalletError::ForeignAddress);
+        }
+        Ok(self
+            .utxos
+            .iter()
+            .filter(|(_, coin)| coin.owner == address)
+            .map(|(coin_id, coin)| (*coin_id, coin.value))
+            .collect())
+    }
+
+    pub(crate) fn coin_details(&self, coin_id: &CoinId) -> Result<Coin, WalletError> {
+        self.utxos
+            .get(coin_id)
+            .cloned()
+            .ok_or(WalletError::UnknownCoin)
+    }
+}
+
+/// Checks that `wallet` agrees with `naive` on every query, for the addresses of `naive`.
+/// `context` is added to every failure message, e.g. the seed and step of a random run.
+pub(crate) fn assert_wallets_equal(wallet: &Wallet, naive: &NaiveWallet, context: &str) {
+    assert_eq!(
+        wallet.best_height(),
+        naive.best_height(),
+        "{context}: best height"
+    );
+    assert_eq!(
+        wallet.best_hash(),
+        naive.best_hash(),
+        "{context}: best hash"
+    );
+    assert_eq!(
+        wallet.net_worth(),
+        naive.net_worth(),
+        "{context}: net worth"
+    );
+    for address in naive.addresses() {
+        assert_eq!(
+            wallet.total_assets_of(address.clone()),
+            naive.total_assets_of(address.clone()),
+            "{context}: total assets of {address:?}"
+        );
+        // The order of the coins is up to the implementation, so compare them as sets
+        let coins = wallet.all_coins_of(address.clone()).unwrap();
+        let naive_coins = naive.all_coins_of(address.clone()).unwrap();
+        assert_eq!(
+            coins.len(),
+            naive_coins.len(),
+            "{context}: number of coins of {address:?}"
+        );
+        for coin in &naive_coins {
+            assert!(
+                coins.contains(coin),
+                "{context}: missing coin {coin:?} of {address:?}"
+            );
+            assert_eq!(
+                wallet.coin_details(&coin.0),
+                naive.coin_details(&coin.0),
+                "{context}: details of coin {coin:?}"
+            );
+        }
+    }
+}
+
+/// Names for the coins a simulated transaction mints, besides its marker coin.
+const MINT_NAMES: [&str; 2] = ["first", "second"];
+
+/// Generates random chains with forks and reorgs on a `RecordingNode`. It uses its own tiny
+/// pseudo random generator instead of extra crates. Nothing in the generated blocks comes
+/// from `Input::dummy()`, which is random, so the same seed always produces the same
+/// blocks and coin ids and a failing run can be replayed.
+pub(crate) struct ChainSimulator {
+    pub(crate) node: RecordingNode,
+    rng_state: u64,
+    /// Every block the simulator created, with the coins that are unspent on the
+    /// chain ending in that block.
+    blocks: Vec<(BlockId, Vec<CoinId>)>,
+    /// Every coin minted so far to a simulated address, on any branch, including
+    /// abandoned ones.
+    minted: Vec<CoinId>,
+    /// Counter used to make every transaction unique.
+    markers: u64,
+}
+
+impl ChainSimulator {
+    pub(crate) fn new(seed: u64) -> Self {
+        ChainSimulator {
+            node: RecordingNode::new(),
+            // The state of xorshift must never be zero
+            rng_state: (seed << 1) | 1,
+            blocks: vec![(Block::genesis().id(), vec![])],
+            minted: vec![],
+            markers: 0,
+        }
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.rng_state ^= self.rng_state << 13;
+        self.rng_state ^= self.rng_state >> 7;
+        self.rng_state ^= self.rng_state << 17;
+        self.rng_state
+    }
+
+    fn random_below(&mut self, bound: u64) -> u64 {
+        self.next_random() % bound
+    }
+
+    /// A transaction that mints a coin to Eve with a value no other marker uses. Eve is
+    /// never a simulated address, so markers make transactions unique without touching
+    /// the balances under test.
+    fn marker(&mut self) -> TxSpec {
+        self.markers += 1;
+        TxSpec::new().mint("marker", Address::Eve, self.markers)
+    }
+
+    /// Adds a random block on top of a random existing block. Most of the time the parent
+    /// is the last block added, so chains grow, but sometimes an older block is chosen,
+    /// which forks the chain and forces the wallet to reorg. Usually the new block becomes
+    /// the best one, but sometimes it is only a side block the wallet has to ignore.
+    pub(crate) fn step(&mut self, addresses: &[Address]) {
+        assert!(
+            !addresses.is_empty() && !addresses.contains(&Address::Eve),
+            "simulated addresses must not be empty and must not include Eve, who owns the markers"
+        );
+
+        let tip_index = self.blocks.len() - 1;
+        let parent_index = if self.random_below(4) == 0 {
+            self.random_below(self.blocks.len() as u64) as usize
+        } else {
+            tip_index
+        };
+        let (parent_id, mut unspent) = self.blocks[parent_index].clone();
+
+        let mut transactions = vec![];
+        for _ in 0..self.random_below(3) {
+            let mut spec = self.marker();
+            if !unspent.is_empty() && self.random_below(2) == 0 {
+                let spent_index = self.random_below(unspent.len() as u64) as usize;
+                spec = spec.spend(unspent.remove(spent_index));
+            }
+            let names = &MINT_NAMES[..self.random_below(3) as usize];
+            for name in names {
+                let owner = addresses[self.random_below(addresses.len() as u64) as usize].clone();
+                let value = 1 + self.random_below(1000);
+                spec = spec.mint(name, owner, value);
+            }
+            let built = spec.build();
+            for name in names {
+                unspent.push(built.coin(name));
+                self.minted.push(built.coin(name));
+            }
+            transactions.push(built.tx);
+        }
+        // Keeps this block different from its siblings even if it has no other transactions
+        transactions.push(self.marker().build().tx);
+
+        let block_id = if self.random_below(5) == 0 {
+            self.node.add_block(parent_id, transactions)
+        } else {
+            self.node.add_block_as_best(parent_id, transactions)
+        };
+        self.blocks.push((block_id, unspent));
+    }
+
+    /// Checks `wallet` against a `NaiveWallet` with the same addresses. Also checks
+    /// `coin_details` for every coin ever minted, so a coin from an abandoned branch that
+    /// the wallet still reports is caught. `context` is added to every failure message,
+    /// e.g. the seed and step of a random run.
+    pub(crate) fn assert_wallet_consistent(
+        &self,
+        wallet: &Wallet,
+        addresses: &[Address],
+        context: &str,
+    ) {
+        let mut naive = NaiveWallet::new(addresses.iter().cloned());
+        naive.sync(&self.node);
+        assert_wallets_equal(wallet, &naive, context);
+
+        for coin_id in &self.minted {
+            assert_eq!(
+                wallet.coin_details(coin_id),
+                naive.coin_details(coin_id),
+                "{context}: details of coin {coin_id:?}"
+            );
+        }
+    }
+}
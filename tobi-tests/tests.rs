@@ -1,5 +1,6 @@
 //! Tests for the bonecoin wallet
 
+use super::testkit::*;
 use super::*;
 
 fn wallet_with_alice() -> Wallet {
@@ -444,20 +445,16 @@ fn reorg_with_utxos_input() {
     // We have a single transaction that consumes some made up input
     // and creates a single output to alice.
     const COIN_VALUE: u64 = 100;
-    let coin = Coin {
-        value: COIN_VALUE,
-        owner: Address::Alice,
-    };
-    let tx = Transaction {
-        inputs: vec![Input::dummy()],
-        outputs: vec![coin.clone()],
-    };
-    let coin_id = tx.coin_id(0);
+    let mint = TxSpec::new()
+        .dummy_input()
+        .mint("alice", Address::Alice, COIN_VALUE)
+        .build();
+    let coin_id = mint.coin("alice");
 
     // Sync a chain to height 3
     let old_b1_id = node.add_block_as_best(Block::genesis().id(), vec![]);
     let old_b2_id = node.add_block_as_best(old_b1_id, vec![]);
-    let _old_b3_id = node.add_block_as_best(old_b2_id, vec![tx]);
+    let _old_b3_id = node.add_block_as_best(old_b2_id, vec![mint.tx]);
     wallet.sync(&node);
 
     // Reorg to shorter chain of length 2
@@ -483,30 +480,20 @@ fn reorg_with_utxos_output() {
     let mut wallet = wallet_with_alice();
 
     const COIN_VALUE: u64 = 100;
-    let coin = Coin {
-        value: COIN_VALUE,
-        owner: Address::Alice,
-    };
-    let mint_tx = Transaction {
-        inputs: vec![Input::dummy()],
-        outputs: vec![coin.clone()],
-    };
-    let coin_id = mint_tx.coin_id(0);
+    let mint = TxSpec::new()
+        .dummy_input()
+        .mint("alice", Address::Alice, COIN_VALUE)
+        .build();
+    let coin_id = mint.coin("alice");
 
-    let spend_tx = Transaction {
-        inputs: vec![Input {
-            coin_id: coin_id.clone(),
-            signature: Signature::Invalid,
-        }],
-        outputs: vec![],
-    };
+    let burn = TxSpec::new().spend(coin_id).burn();
 
     // Sync a chain to height 5
-    let old_b1_id = node.add_block_as_best(Block::genesis().id(), vec![mint_tx]);
+    let old_b1_id = node.add_block_as_best(Block::genesis().id(), vec![mint.tx]);
     let old_b2_id = node.add_block_as_best(old_b1_id, vec![]);
     let old_b3_id = node.add_block_as_best(old_b2_id, vec![]);
 
-    let old_b4_id = node.add_block_as_best(old_b3_id, vec![spend_tx]);
+    let old_b4_id = node.add_block_as_best(old_b3_id, vec![burn.tx]);
     let _old_b5_id = node.add_block_as_best(old_b4_id, vec![]);
     wallet.sync(&node);
 
@@ -531,7 +518,13 @@ fn reorg_with_utxos_output() {
         wallet.all_coins_of(Address::Alice),
         Ok(vec![(coin_id, COIN_VALUE)])
     );
-    assert_eq!(wallet.coin_details(&coin_id), Ok(coin));
+    assert_eq!(
+        wallet.coin_details(&coin_id),
+        Ok(Coin {
+            value: COIN_VALUE,
+            owner: Address::Alice,
+        })
+    );
 }
 
 // Reorgs with utxos in the chain history
@@ -542,33 +535,23 @@ fn reorg_with_utxos_complete() {
     let mut wallet = wallet_with_alice_and_bob();
 
     const COIN_VALUE: u64 = 100;
-    let coin = Coin {
-        value: COIN_VALUE,
-        owner: Address::Alice,
-    };
-    let mint_tx = Transaction {
-        inputs: vec![Input::dummy()],
-        outputs: vec![coin.clone()],
-    };
-    let coin_id = mint_tx.coin_id(0);
+    let mint = TxSpec::new()
+        .dummy_input()
+        .mint("alice", Address::Alice, COIN_VALUE)
+        .build();
+    let coin_id = mint.coin("alice");
 
-    let spend_tx = Transaction {
-        inputs: vec![Input {
-            coin_id: coin_id.clone(),
-            signature: Signature::Invalid,
-        }],
-        outputs: vec![Coin {
-            value: COIN_VALUE,
-            owner: Address::Bob,
-        }],
-    };
+    let spend = TxSpec::new()
+        .spend(coin_id)
+        .mint("bob", Address::Bob, COIN_VALUE)
+        .build();
 
     // Sync a chain to height 5
-    let old_b1_id = node.add_block_as_best(Block::genesis().id(), vec![mint_tx]);
+    let old_b1_id = node.add_block_as_best(Block::genesis().id(), vec![mint.tx]);
     let old_b2_id = node.add_block_as_best(old_b1_id, vec![]);
     let old_b3_id = node.add_block_as_best(old_b2_id, vec![]);
 
-    let old_b4_id = node.add_block_as_best(old_b3_id, vec![spend_tx]);
+    let old_b4_id = node.add_block_as_best(old_b3_id, vec![spend.tx]);
     let _old_b5_id = node.add_block_as_best(old_b4_id, vec![]);
     wallet.sync(&node);
 
@@ -596,7 +579,13 @@ fn reorg_with_utxos_complete() {
         Ok(vec![(coin_id, COIN_VALUE)])
     );
     assert_eq!(wallet.all_coins_of(Address::Bob), Ok(vec![]));
-    assert_eq!(wallet.coin_details(&coin_id), Ok(coin));
+    assert_eq!(
+        wallet.coin_details(&coin_id),
+        Ok(Coin {
+            value: COIN_VALUE,
+            owner: Address::Alice,
+        })
+    );
 }
 
 #[test]
@@ -1513,3 +1502,86 @@ fn unknown_coin_test() {
         Err(WalletError::UnknownCoin)
     );
 }
+
+/// The next tests make sure the `TxSpec` test helper itself rejects mistakes loudly
+#[test]
+#[should_panic(expected = "used twice")]
+fn tx_spec_rejects_duplicate_names() {
+    TxSpec::new()
+        .mint("alice", Address::Alice, 100)
+        .mint("alice", Address::Alice, 200);
+}
+
+#[test]
+#[should_panic(expected = "no coin named bob")]
+fn tx_spec_rejects_unknown_names() {
+    TxSpec::new()
+        .mint("alice", Address::Alice, 100)
+        .build()
+        .coin("bob");
+}
+
+#[test]
+#[should_panic(expected = "spend at least one coin")]
+fn tx_spec_burn_needs_an_input() {
+    TxSpec::new().mint("alice", Address::Alice, 100).burn();
+}
+
+/// Runs many random forks and reorgs and checks after every sync that the wallet is in the
+/// same state as a naive wallet that rescans the best chain from genesis. This generalizes
+/// the hand-written reorg tests.
+#[test]
+fn random_reorgs_match_naive_wallet() {
+    let addresses = vec![Address::Alice, Address::Bob, Address::Charlie];
+
+    for seed in 0..10 {
+        let mut simulator = ChainSimulator::new(seed);
+        let mut wallet = wallet_with_addresses(addresses.clone());
+
+        for step in 0..30 {
+            simulator.step(&addresses);
+            wallet.sync(&simulator.node.mock);
+            simulator.assert_wallet_consistent(
+                &wallet,
+                &addresses,
+                &format!("seed {seed}, step {step}"),
+            );
+        }
+    }
+}
+
+/// Hand-written reorg checked against the naive wallet: Alice's coin is minted on the old
+/// chain, spent on it and only minted again on the new one.
+#[test]
+fn reorg_matches_naive_wallet() {
+    let mut node = RecordingNode::new();
+    let mut wallet = wallet_with_alice_and_bob();
+    let mut naive = NaiveWallet::new(vec![Address::Alice, Address::Bob]);
+
+    let mint = TxSpec::new()
+        .dummy_input()
+        .mint("alice", Address::Alice, 100)
+        .build();
+    let spend = TxSpec::new()
+        .spend(mint.coin("alice"))
+        .mint("bob", Address::Bob, 100)
+        .build();
+    let bob_coin = spend.coin("bob");
+    let old_b1_id = node.add_block_as_best(Block::genesis().id(), vec![mint.tx.clone()]);
+    let _old_b2_id = node.add_block_as_best(old_b1_id, vec![spend.tx]);
+    wallet.sync(&node.mock);
+    naive.sync(&node);
+    assert_wallets_equal(&wallet, &naive, "before the reorg");
+
+    let b1_id = node.add_block_as_best(Block::genesis().id(), vec![marker_tx()]);
+    let b2_id = node.add_block_as_best(b1_id, vec![mint.tx]);
+    let _b3_id = node.add_block_as_best(b2_id, vec![]);
+    wallet.sync(&node.mock);
+    naive.sync(&node);
+    assert_wallets_equal(&wallet, &naive, "after the reorg");
+    assert_eq!(naive.total_assets_of(Address::Alice), Ok(100));
+    assert_eq!(
+        wallet.coin_details(&bob_coin),
+        Err(WalletError::UnknownCoin)
+    );
+}